@@ -0,0 +1,79 @@
+//! `lsof`-based backend, kept as a fallback for hosts without a `/proc/net` to parse.
+//! Shells out to `lsof` on every query, so it's considerably slower than the native
+//! backend and requires `lsof` to be installed.
+
+use std::error::Error;
+use std::io::{BufRead, BufReader, Cursor};
+use std::net::IpAddr;
+use std::path::Path;
+
+use tokio::net::process::Command;
+
+/// Look up the username owning the connection identified by `local_port`, `remote_port`
+/// and `remote_ip` by asking `lsof` (found at `lsof_path`) for the matching socket.
+pub async fn find_user(
+    lsof_path: &Path,
+    local_port: u16,
+    remote_port: u16,
+    remote_ip: IpAddr,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    let lsof_output = run_lsof(lsof_path, remote_port, remote_ip).await?;
+    Ok(search_for_port(local_port, lsof_output))
+}
+
+/// Invoke `lsof` to find all connections to a host/port combination and return stdout
+async fn run_lsof(
+    lsof_path: &Path,
+    remote_port: u16,
+    remote_host: IpAddr,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    // `remote_host` will be plain v4 under `--stack v4`, otherwise either v6 or ipv6-mapped-v4
+    // Use whatever address family the client used to contact the identd
+    let lsof_target_arg = match remote_host {
+        IpAddr::V4(ip) => format!("4TCP@{}:{}", ip, remote_port),
+        IpAddr::V6(ip) => match ip.to_ipv4() {
+            Some(v4) if ip.segments()[0..6] == [0, 0, 0, 0, 0, 0xffff] => {
+                format!("4TCP@{}:{}", v4, remote_port)
+            }
+            _ => format!("6TCP@[{}]:{}", ip, remote_port),
+        },
+    };
+    Ok(Command::new(lsof_path)
+        .arg("-i")
+        .arg(lsof_target_arg)
+        .arg("-F")
+        .arg("Ln")
+        .arg("-n")
+        .output()
+        .await?
+        .stdout)
+}
+
+/// Parse `lsof` output and search for the given local port. If found, return the corresponding username.
+fn search_for_port(local_port: u16, lsof_output: Vec<u8>) -> Option<String> {
+    let mut reader = BufReader::new(Cursor::new(lsof_output));
+    let mut current_user: Option<String> = None;
+    let mut matching_user: Option<String> = None;
+    let target = format!(":{}->", local_port);
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(n) if n > 0 => (),
+            _ => break,
+        };
+        let first = line.chars().next();
+        match first {
+            Some('L') => {
+                current_user = Some(line[1..].trim().to_owned());
+            }
+            Some('n') => {
+                if line.contains(&target) {
+                    matching_user = current_user;
+                    break;
+                }
+            }
+            _ => (),
+        };
+    }
+    matching_user
+}
@@ -0,0 +1,37 @@
+//! Backend implementations for discovering which local user owns a TCP connection.
+//!
+//! The default backend parses `/proc/net/tcp{,6}` directly, which avoids the
+//! fork-per-query cost and the external dependency of shelling out to `lsof`.
+//! Building with the `lsof` feature makes the original `lsof`-based backend
+//! available too, as a fallback for hosts where `/proc/net` isn't available; which
+//! one is used at runtime is chosen via [`crate::config::Backend`].
+
+#[cfg(feature = "lsof")]
+mod lsof;
+mod proc_net;
+
+use crate::config::Backend;
+use std::error::Error;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Look up the username owning the connection identified by `local_port`,
+/// `remote_port` and `remote_ip`, using whichever backend `backend` selects.
+pub async fn find_user(
+    backend: Backend,
+    lsof_path: &Path,
+    local_port: u16,
+    remote_port: u16,
+    remote_ip: IpAddr,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    match backend {
+        Backend::Proc => proc_net::find_user(local_port, remote_port, remote_ip).await,
+        #[cfg(feature = "lsof")]
+        Backend::Lsof => lsof::find_user(lsof_path, local_port, remote_port, remote_ip).await,
+        #[cfg(not(feature = "lsof"))]
+        Backend::Lsof => {
+            let _ = lsof_path;
+            Err("this binary was not built with the `lsof` feature".into())
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Native Linux backend that reads `/proc/net/tcp` and `/proc/net/tcp6` directly,
+//! the way bandwhich's socket inventory works, instead of shelling out to `lsof`.
+
+use std::error::Error;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Look up the username owning the connection identified by `local_port`, `remote_port`
+/// and `remote_ip`, by scanning `/proc/net/tcp{,6}` for the matching row and reading the
+/// socket's uid straight out of it (field 7 — no need to walk every process's open fds
+/// looking for the inode).
+///
+/// With the default dual-stack listener, `remote_ip` (the address of whoever is asking
+/// us to identify a connection) arrives as IPv6, mapped (`::ffff:a.b.c.d`) for IPv4
+/// callers; with `--stack v4` it arrives as plain IPv4. But the connection being identified may be a genuine IPv4 socket
+/// living in `/proc/net/tcp`, so both tables are checked: `/proc/net/tcp` with the
+/// address unmapped back to IPv4, and `/proc/net/tcp6` with the address as given.
+pub async fn find_user(
+    local_port: u16,
+    remote_port: u16,
+    remote_ip: IpAddr,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    let v4_addr = unmap_ipv4(remote_ip);
+    let uid = if let Some(uid) = find_uid("/proc/net/tcp", local_port, remote_port, v4_addr)? {
+        Some(uid)
+    } else {
+        find_uid("/proc/net/tcp6", local_port, remote_port, remote_ip)?
+    };
+    Ok(uid.and_then(username_for_uid))
+}
+
+/// If `ip` is an IPv4-mapped IPv6 address (`::ffff:a.b.c.d`), return its unmapped IPv4
+/// form; otherwise return `ip` unchanged.
+fn unmap_ipv4(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) if v6.segments()[0..6] == [0, 0, 0, 0, 0, 0xffff] => {
+            let octets = v6.octets();
+            IpAddr::V4(Ipv4Addr::new(octets[12], octets[13], octets[14], octets[15]))
+        }
+        other => other,
+    }
+}
+
+/// Scan a `/proc/net/tcp{,6}` table for the row matching the given local/remote endpoint
+/// and return the uid that owns that socket (field 7).
+fn find_uid(
+    path: &str,
+    local_port: u16,
+    remote_port: u16,
+    remote_ip: IpAddr,
+) -> Result<Option<u32>, Box<dyn Error + Send + Sync>> {
+    let contents = fs::read_to_string(path)?;
+    for line in contents.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 8 {
+            continue;
+        }
+        let (_, l_port) = parse_hex_addr(fields[1])?;
+        let (r_addr, r_port) = parse_hex_addr(fields[2])?;
+        if l_port == local_port && r_port == remote_port && r_addr == remote_ip {
+            return Ok(Some(fields[7].parse()?));
+        }
+    }
+    Ok(None)
+}
+
+/// Parse a `/proc/net/tcp{,6}` `<address>:<port>` field, e.g. `0100007F:1F90`.
+fn parse_hex_addr(field: &str) -> Result<(IpAddr, u16), Box<dyn Error + Send + Sync>> {
+    let mut parts = field.split(':');
+    let addr_hex = parts.next().ok_or("missing address in /proc/net/tcp field")?;
+    let port_hex = parts.next().ok_or("missing port in /proc/net/tcp field")?;
+    let port = u16::from_str_radix(port_hex, 16)?;
+    let addr = match addr_hex.len() {
+        8 => {
+            let bits = u32::from_str_radix(addr_hex, 16)?;
+            IpAddr::V4(Ipv4Addr::from(bits.to_le_bytes()))
+        }
+        32 => {
+            let mut bytes = [0u8; 16];
+            for (i, word_hex) in addr_hex.as_bytes().chunks(8).enumerate() {
+                let word = u32::from_str_radix(std::str::from_utf8(word_hex)?, 16)?;
+                bytes[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+            }
+            IpAddr::V6(Ipv6Addr::from(bytes))
+        }
+        _ => return Err("unrecognised address field length in /proc/net/tcp".into()),
+    };
+    Ok((addr, port))
+}
+
+/// Resolve a uid to a username via the system passwd database.
+fn username_for_uid(uid: u32) -> Option<String> {
+    let passwd = unsafe { libc::getpwuid(uid) };
+    if passwd.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr((*passwd).pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hex_addr_decodes_ipv4() {
+        let (addr, port) = parse_hex_addr("0100007F:1F90").unwrap();
+        assert_eq!(addr, IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+        assert_eq!(port, 8080);
+    }
+
+    #[test]
+    fn parse_hex_addr_decodes_ipv6() {
+        // ::1 encoded as four little-endian 32-bit words, the way /proc/net/tcp6 does it.
+        let (addr, port) = parse_hex_addr("00000000000000000000000001000000:0050").unwrap();
+        assert_eq!(addr, IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(port, 80);
+    }
+
+    #[test]
+    fn parse_hex_addr_rejects_unrecognised_length() {
+        assert!(parse_hex_addr("ABCD:1F90").is_err());
+    }
+
+    /// Write `contents` to a fresh temp file and return its path, for feeding fixture
+    /// `/proc/net/tcp`-style tables to `find_uid` without touching the real `/proc`.
+    fn write_fixture(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("whoisit_test_{}", name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    const FIXTURE_TABLE: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+         0: 0100007F:1F90 0100007F:0050 01 00000000:00000000 00:00000000 00000000  1000        0 12345 1 0000000000000000 20 0 0 10 0\n";
+
+    #[test]
+    fn find_uid_matches_row_in_fixture_table() {
+        let path = write_fixture("find_uid_match", FIXTURE_TABLE);
+        let uid = find_uid(
+            path.to_str().unwrap(),
+            8080,
+            80,
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+        )
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(uid, Some(1000));
+    }
+
+    #[test]
+    fn find_uid_returns_none_when_no_row_matches() {
+        let path = write_fixture("find_uid_miss", FIXTURE_TABLE);
+        let uid = find_uid(
+            path.to_str().unwrap(),
+            9999,
+            80,
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+        )
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(uid, None);
+    }
+}
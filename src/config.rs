@@ -0,0 +1,206 @@
+//! Command-line configuration for the identd server.
+
+use clap::{ArgEnum, Parser, Subcommand};
+use socket2::{Domain, Socket, Type};
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener as StdTcpListener};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::response::{self, ResponseMode};
+
+/// An identd implementation for Linux.
+#[derive(Parser, Debug)]
+#[clap(name = "whoisit")]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    #[clap(flatten)]
+    pub config: Config,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Decode a previously-issued `OTHER` token back into its original fields.
+    Decode {
+        /// The hex-encoded token from a client's USERID response.
+        token: String,
+        /// The hex-encoded AES-256 key the token was encrypted with.
+        key: String,
+    },
+}
+
+/// Which IP stack(s) the server listens on.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stack {
+    /// IPv4 only.
+    V4,
+    /// IPv6 only.
+    V6,
+    /// The IPv6 wildcard, which also accepts IPv4-mapped connections.
+    Dual,
+}
+
+/// Which backend resolves a connection to the username that owns it.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Parse `/proc/net/tcp{,6}` directly.
+    Proc,
+    /// Shell out to `lsof`. Only available when built with the `lsof` feature.
+    Lsof,
+}
+
+/// Which RFC 1413 reply format to use, selected via `--reply-mode`.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplyMode {
+    /// `USERID : UNIX : <username>` — reveals the real username.
+    Unix,
+    /// `USERID : OTHER : <token>` — an opaque, encrypted token in place of the
+    /// username. Requires `--key`.
+    Other,
+}
+
+#[derive(Parser, Debug)]
+pub struct Config {
+    /// Address to bind the identd listener to. Defaults to the wildcard address for
+    /// `--stack`. Incompatible with `--stack dual`, which always binds the IPv6
+    /// wildcard and has no single address `--bind` could narrow it to.
+    #[clap(long)]
+    pub bind: Option<IpAddr>,
+
+    /// Port to listen on.
+    #[clap(long, default_value_t = 113)]
+    pub port: u16,
+
+    /// Which IP stack(s) to listen on.
+    #[clap(long, arg_enum, default_value = "dual")]
+    pub stack: Stack,
+
+    /// Which backend resolves a connection to its owning user.
+    #[clap(long, arg_enum, default_value = "proc")]
+    pub backend: Backend,
+
+    /// Path to the `lsof` binary, used when `--backend lsof` is selected.
+    #[clap(long, default_value = "lsof")]
+    pub lsof_path: PathBuf,
+
+    /// How long to wait for a client to send its query before dropping the connection.
+    #[clap(long, default_value_t = 5)]
+    pub timeout_secs: u64,
+
+    /// Maximum number of client connections handled concurrently.
+    #[clap(long, default_value_t = 256)]
+    pub max_connections: usize,
+
+    /// Which RFC 1413 reply format to use.
+    #[clap(long, arg_enum, default_value = "unix")]
+    pub reply_mode: ReplyMode,
+
+    /// Hex-encoded AES-256 key used to encrypt `OTHER` tokens. Required when
+    /// `--reply-mode other` is selected.
+    #[clap(long, required_if_eq("reply_mode", "other"))]
+    pub key: Option<String>,
+}
+
+impl Config {
+    /// Reject option combinations that can't be honoured instead of silently ignoring
+    /// part of them.
+    pub fn validate(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.stack == Stack::Dual && self.bind.is_some() {
+            return Err(concat!(
+                "--bind is incompatible with --stack dual, which always binds the ",
+                "IPv6 wildcard; pass --stack v4 or --stack v6 to bind a specific address"
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// The address to bind the listening socket to, accounting for `--stack`.
+    pub fn bind_addr(&self) -> SocketAddr {
+        let ip = match self.stack {
+            Stack::Dual => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            Stack::V4 => self.bind.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            Stack::V6 => self.bind.unwrap_or(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        };
+        SocketAddr::new(ip, self.port)
+    }
+
+    /// Bind and start listening on [`bind_addr`](Self::bind_addr), returning a standard
+    /// library listener ready to be handed to `tokio::net::TcpListener::from_std`.
+    ///
+    /// `tokio::net::TcpListener::bind` has no way to set `IPV6_V6ONLY`, so `--stack v6`
+    /// would otherwise bind the same IPv6 wildcard as `dual` and still accept
+    /// IPv4-mapped callers. Build the socket manually via `socket2` so that case can
+    /// set the option explicitly before binding.
+    pub fn bind_listener(&self) -> std::io::Result<StdTcpListener> {
+        let addr = self.bind_addr();
+        let domain = if addr.is_ipv4() { Domain::IPV4 } else { Domain::IPV6 };
+        let socket = Socket::new(domain, Type::STREAM, None)?;
+        if let Stack::V6 = self.stack {
+            socket.set_only_v6(true)?;
+        }
+        socket.set_reuse_address(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(1024)?;
+        socket.set_nonblocking(true)?;
+        Ok(socket.into())
+    }
+
+    /// How long to wait for a client to send its query before dropping the connection.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    /// Build the [`ResponseMode`] selected by `--reply-mode` (and `--key`, for `other`).
+    pub fn response_mode(&self) -> Result<ResponseMode, Box<dyn Error + Send + Sync>> {
+        match self.reply_mode {
+            ReplyMode::Unix => Ok(ResponseMode::Unix),
+            ReplyMode::Other => {
+                let key_hex = self
+                    .key
+                    .as_deref()
+                    .ok_or("--key is required when --reply-mode is set to other")?;
+                Ok(ResponseMode::Other {
+                    key: response::key_from_hex(key_hex)?,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reply_mode_other_without_key_is_rejected_not_panicked() {
+        let result = Cli::try_parse_from(["whoisit", "--reply-mode", "other"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn reply_mode_other_with_key_parses() {
+        let cli = Cli::try_parse_from(["whoisit", "--reply-mode", "other", "--key", "aa"]).unwrap();
+        assert_eq!(cli.config.key.as_deref(), Some("aa"));
+    }
+
+    #[test]
+    fn bind_with_stack_dual_is_rejected_even_when_unspecified() {
+        let cli = Cli::try_parse_from(["whoisit", "--stack", "dual", "--bind", "0.0.0.0"]).unwrap();
+        assert!(cli.config.validate().is_err());
+    }
+
+    #[test]
+    fn bind_with_stack_v4_is_accepted() {
+        let cli = Cli::try_parse_from(["whoisit", "--stack", "v4", "--bind", "0.0.0.0"]).unwrap();
+        assert!(cli.config.validate().is_ok());
+    }
+
+    #[test]
+    fn stack_dual_without_bind_is_accepted() {
+        let cli = Cli::try_parse_from(["whoisit", "--stack", "dual"]).unwrap();
+        assert!(cli.config.validate().is_ok());
+    }
+}
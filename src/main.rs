@@ -1,44 +1,84 @@
 //! whoisit
 //!
 //! An identd implementation for Linux, built as an excuse to play with async/await.
-//! It cheats somewhat by relying on `lsof` to locate the user who owns a given
-//! connection.
+//! It locates the user who owns a given connection by parsing `/proc/net/tcp{,6}`
+//! directly; building with the `lsof` feature makes a fallback backend that shells
+//! out to `lsof` available too. Bind address, backend, timeout and concurrency
+//! limits are all configurable on the command line; see [`config::Cli`].
 //!
 //! On the bright side, it should be compliant with RFC 1413 and it supports queries
 //! from both IPv4 and IPv6 remote hosts.
 
+use clap::Parser;
 use futures::{SinkExt, StreamExt};
 use tokio::codec::{Framed, LinesCodec};
-use tokio::net::process::Command;
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
 
 use std::error::Error;
 use std::fmt;
-use std::io::{BufRead, BufReader, Cursor};
-use std::net::IpAddr;
+use std::sync::Arc;
+
+mod backend;
+mod config;
+mod passwd;
+mod response;
+
+use config::{Cli, Command, Config};
+use response::ResponseMode;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let binding = ":::113";
-    let mut listener = TcpListener::bind(&binding).await?;
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Decode { token, key }) => decode_token(&token, &key),
+        None => serve(cli.config).await,
+    }
+}
+
+/// Decode a previously-issued `OTHER` token back into its original fields, for an
+/// administrator who needs to attribute a connection after the fact.
+fn decode_token(token: &str, key_hex: &str) -> Result<(), Box<dyn Error>> {
+    let key = response::key_from_hex(key_hex)?;
+    let decoded = response::decode_token(&key, token)?;
+    println!("{:#?}", decoded);
+    Ok(())
+}
+
+async fn serve(config: Config) -> Result<(), Box<dyn Error>> {
+    config.validate()?;
+    let response_mode = config.response_mode()?;
+    let config = Arc::new(config);
+    let mut listener = TcpListener::from_std(config.bind_listener()?)?;
+    let connections = Arc::new(Semaphore::new(config.max_connections));
 
     loop {
         let (socket, _) = listener.accept().await?;
+        let permit = connections.clone().acquire_owned().await?;
+        let config = config.clone();
+        let response_mode = response_mode.clone();
         tokio::spawn(async move {
-            let _ = handle_client(socket).await;
+            let _ = handle_client(socket, config, response_mode).await;
+            drop(permit);
         });
     }
 }
 
-async fn handle_client(socket: TcpStream) -> Result<(), Box<dyn Error + Send + Sync>> {
+async fn handle_client(
+    socket: TcpStream,
+    config: Arc<Config>,
+    response_mode: ResponseMode,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
     let remote_ip = socket.peer_addr()?.ip();
     let mut client = Framed::new(socket, LinesCodec::new_with_max_length(1024));
 
-    // Read one line of query
+    // Read one line of query, dropping the connection if the client takes too long
     // LinesCodec will accept either the required \r\n or a plain \n
-    let query = match client.next().await {
-        Some(Ok(q)) => q,
-        _ => return Err(IdentError::NoQuery.into()),
+    let query = match timeout(config.timeout(), client.next()).await {
+        Ok(Some(Ok(q))) => q,
+        Ok(_) => return Err(IdentError::NoQuery.into()),
+        Err(_) => return Err(IdentError::Timeout.into()),
     };
 
     // Parse the requested source and destination ports
@@ -46,29 +86,51 @@ async fn handle_client(socket: TcpStream) -> Result<(), Box<dyn Error + Send + S
     let (local_port, remote_port) = match parse_query(&query) {
         Ok((l, p)) => (l, p),
         Err(e) => {
-            let response = format!("{} : ERROR : INVALID-PORT\r", query);
-            client.send(response).await?;
+            respond_error(&mut client, &query, IdentError::InvalidPort).await?;
             return Err(e);
         }
     };
 
-    // Use lsof to get all connections to that remote host and port
-    let lsof_output = run_lsof(remote_port, remote_ip).await?;
-
-    // Search within that for a user connecting from the specified local port
-    match search_for_port(local_port, lsof_output) {
-        Some(user) => {
-            let response = format!("{} : USERID : UNIX : {}\r", query, user);
-            client.send(response).await?;
+    // Find the user who owns the connection from the specified local port to the
+    // requested remote host and port
+    let lookup = backend::find_user(
+        config.backend,
+        &config.lsof_path,
+        local_port,
+        remote_port,
+        remote_ip,
+    )
+    .await;
+    match lookup {
+        Ok(Some(user)) if passwd::has_opted_out(&user) => {
+            respond_error(&mut client, &query, IdentError::HiddenUser).await?;
         }
-        None => {
-            let response = format!("{} : ERROR : NO-USER\r", query);
+        Ok(Some(user)) => {
+            let userid = response_mode.format_userid(&user, local_port, remote_port)?;
+            let response = format!("{} : USERID : {}\r", query, userid);
             client.send(response).await?;
         }
+        Ok(None) => {
+            respond_error(&mut client, &query, IdentError::NoUser).await?;
+        }
+        Err(e) => {
+            respond_error(&mut client, &query, IdentError::UnknownError).await?;
+            return Err(e);
+        }
     };
     Ok(())
 }
 
+/// Send `<query> : ERROR : <token>\r` for the wire token corresponding to `err`.
+async fn respond_error(
+    client: &mut Framed<TcpStream, LinesCodec>,
+    query: &str,
+    err: IdentError,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let response = format!("{} : ERROR : {}\r", query, err.wire_token());
+    client.send(response).await
+}
+
 /// Parse two comma-separated port numbers, ignoring whitespace
 fn parse_query(query: &str) -> Result<(u16, u16), Box<dyn Error + Send + Sync>> {
     let ports: Vec<&str> = query.split(",").map(|s| s.trim()).collect();
@@ -78,65 +140,29 @@ fn parse_query(query: &str) -> Result<(u16, u16), Box<dyn Error + Send + Sync>>
     Ok((ports[0].parse()?, ports[1].parse()?))
 }
 
-/// Invoke `lsof` to find all connections to a host/port combination and return stdout
-async fn run_lsof(remote_port: u16, remote_host: IpAddr) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
-    // Since we bind to IPv6, realistically `remote_host` will be either v6 or ipv6-mapped-v4
-    // Use whatever address family the client used to contact the identd
-    let lsof_target_arg = match remote_host {
-        IpAddr::V4(ip) => format!("4TCP@{}:{}", ip, remote_port),
-        IpAddr::V6(ip) => {
-            match ip.to_ipv4() {
-                Some(v4) if ip.segments()[0..6] == [0, 0, 0, 0, 0, 0xffff] => {
-                    format!("4TCP@{}:{}", v4, remote_port)
-                },
-                _ => format!("6TCP@[{}]:{}", ip, remote_port)
-            }
-        }
-    };
-    Ok(Command::new("lsof")
-        .arg("-i")
-        .arg(lsof_target_arg)
-        .arg("-F")
-        .arg("Ln")
-        .arg("-n")
-        .output()
-        .await?
-        .stdout)
-}
-
-/// Parse `lsof` output and search for the given local port. If found, return the corresponding username.
-fn search_for_port(local_port: u16, lsof_output: Vec<u8>) -> Option<String> {
-    let mut reader = BufReader::new(Cursor::new(lsof_output));
-    let mut current_user: Option<String> = None;
-    let mut matching_user: Option<String> = None;
-    let target = format!(":{}->", local_port);
-    loop {
-        let mut line = String::new();
-        match reader.read_line(&mut line) {
-            Ok(n) if n > 0 => (),
-            _ => break,
-        };
-        let first = line.chars().next();
-        match first {
-            Some('L') => {
-                current_user = Some(line[1..].trim().to_owned());
-            }
-            Some('n') => {
-                if line.contains(&target) {
-                    matching_user = current_user;
-                    break;
-                }
-            }
-            _ => (),
-        };
-    }
-    matching_user
-}
-
 #[derive(Debug)]
 enum IdentError {
     NoQuery,
     InvalidPort,
+    Timeout,
+    NoUser,
+    HiddenUser,
+    UnknownError,
+}
+
+impl IdentError {
+    /// The RFC 1413 error token reported to the client for this condition. `NoQuery`
+    /// and `Timeout` occur before a query was received to echo back in a response, so
+    /// they're never actually sent over the wire.
+    fn wire_token(&self) -> &'static str {
+        match self {
+            IdentError::NoQuery | IdentError::Timeout => "UNKNOWN-ERROR",
+            IdentError::InvalidPort => "INVALID-PORT",
+            IdentError::NoUser => "NO-USER",
+            IdentError::HiddenUser => "HIDDEN-USER",
+            IdentError::UnknownError => "UNKNOWN-ERROR",
+        }
+    }
 }
 
 impl Error for IdentError {
@@ -144,6 +170,10 @@ impl Error for IdentError {
         match *self {
             IdentError::NoQuery => "no query received from client",
             IdentError::InvalidPort => "invalid port specification in query",
+            IdentError::Timeout => "client did not send a query within the timeout",
+            IdentError::NoUser => "no user found for the requested ports",
+            IdentError::HiddenUser => "user has opted out of identification",
+            IdentError::UnknownError => "internal error while resolving the connection",
         }
     }
 }
@@ -0,0 +1,25 @@
+//! Shared helpers for querying the system passwd database by username, independent of
+//! which backend resolved the connection to that username.
+
+use std::ffi::{CStr, CString};
+use std::path::PathBuf;
+
+/// Look up a user's home directory via `getpwnam`.
+pub fn home_dir(username: &str) -> Option<PathBuf> {
+    let cname = CString::new(username).ok()?;
+    let passwd = unsafe { libc::getpwnam(cname.as_ptr()) };
+    if passwd.is_null() {
+        return None;
+    }
+    let home = unsafe { CStr::from_ptr((*passwd).pw_dir) };
+    Some(PathBuf::from(home.to_string_lossy().into_owned()))
+}
+
+/// Whether `username` has opted out of being identified, by placing a `.noident`
+/// marker file in their home directory.
+pub fn has_opted_out(username: &str) -> bool {
+    match home_dir(username) {
+        Some(home) => home.join(".noident").exists(),
+        None => false,
+    }
+}
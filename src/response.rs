@@ -0,0 +1,197 @@
+//! Builds the `USERID` response line for a successful identd lookup.
+//!
+//! RFC 1413 defines two reply formats: `UNIX` reveals the real username, while
+//! `OTHER` lets the operator return an opaque token instead, so a remote host can't
+//! enumerate local accounts simply by probing ports. The `Other` variant encrypts a
+//! record of `timestamp || local_port || remote_port || username` with an
+//! admin-configured key; [`decode_token`] recovers the fields again for an
+//! administrator who needs to attribute a connection after the fact.
+
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use rand::RngCore;
+use std::convert::TryInto;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Length of the AES-GCM nonce prepended to every token, in bytes.
+const NONCE_LEN: usize = 12;
+
+/// How a successful identd lookup is rendered back to the client.
+#[derive(Clone)]
+pub enum ResponseMode {
+    /// `USERID : UNIX : <username>` — reveals the real username.
+    Unix,
+    /// `USERID : OTHER : <token>` — an opaque, encrypted token in place of the username.
+    Other { key: [u8; 32] },
+}
+
+impl ResponseMode {
+    /// Render the `<opsys> : <info>` portion of a successful `USERID` response for
+    /// `username`, which was matched on `local_port`/`remote_port`.
+    pub fn format_userid(
+        &self,
+        username: &str,
+        local_port: u16,
+        remote_port: u16,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        match self {
+            ResponseMode::Unix => Ok(format!("UNIX : {}", username)),
+            ResponseMode::Other { key } => {
+                let token = encode_token(key, local_port, remote_port, username)?;
+                Ok(format!("OTHER : {}", token))
+            }
+        }
+    }
+}
+
+/// A decoded `OTHER` token: who was matched, when, and on which ports.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DecodedToken {
+    pub timestamp: u64,
+    pub local_port: u16,
+    pub remote_port: u16,
+    pub username: String,
+}
+
+/// Encrypt a `timestamp || local_port || remote_port || username` record under `key`
+/// and hex-encode the nonce-prefixed ciphertext for use as an `OTHER` token.
+fn encode_token(
+    key: &[u8; 32],
+    local_port: u16,
+    remote_port: u16,
+    username: &str,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let record = encode_record(timestamp, local_port, remote_port, username);
+
+    let cipher = Aes256Gcm::new(Key::from_slice(key));
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut nonce_bytes);
+    let nonce = Nonce::from_slice(&nonce_bytes);
+    let ciphertext = cipher
+        .encrypt(nonce, record.as_ref())
+        .map_err(|_| "failed to encrypt ident token")?;
+
+    let mut payload = nonce_bytes.to_vec();
+    payload.extend_from_slice(&ciphertext);
+    Ok(hex_encode(&payload))
+}
+
+/// Recover the original `(timestamp, local_port, remote_port, username)` record from a
+/// hex-encoded `OTHER` token, given the key it was encrypted with.
+pub fn decode_token(key: &[u8; 32], token: &str) -> Result<DecodedToken, Box<dyn Error + Send + Sync>> {
+    let payload = hex_decode(token)?;
+    if payload.len() < NONCE_LEN {
+        return Err("token is shorter than the nonce it must carry".into());
+    }
+    let (nonce_bytes, ciphertext) = payload.split_at(NONCE_LEN);
+
+    let cipher = Aes256Gcm::new(Key::from_slice(key));
+    let nonce = Nonce::from_slice(nonce_bytes);
+    let record = cipher
+        .decrypt(nonce, ciphertext)
+        .map_err(|_| "failed to decrypt ident token: wrong key or corrupt token")?;
+
+    let (timestamp, local_port, remote_port, username) = decode_record(&record)?;
+    Ok(DecodedToken {
+        timestamp,
+        local_port,
+        remote_port,
+        username,
+    })
+}
+
+/// Pack the record fields into bytes: 8-byte timestamp, 2-byte local port, 2-byte
+/// remote port, then the username taking up the remainder of the buffer.
+fn encode_record(timestamp: u64, local_port: u16, remote_port: u16, username: &str) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(12 + username.len());
+    buf.extend_from_slice(&timestamp.to_be_bytes());
+    buf.extend_from_slice(&local_port.to_be_bytes());
+    buf.extend_from_slice(&remote_port.to_be_bytes());
+    buf.extend_from_slice(username.as_bytes());
+    buf
+}
+
+/// Inverse of [`encode_record`].
+fn decode_record(buf: &[u8]) -> Result<(u64, u16, u16, String), Box<dyn Error + Send + Sync>> {
+    if buf.len() < 12 {
+        return Err("ident token record is too short".into());
+    }
+    let timestamp = u64::from_be_bytes(buf[0..8].try_into()?);
+    let local_port = u16::from_be_bytes(buf[8..10].try_into()?);
+    let remote_port = u16::from_be_bytes(buf[10..12].try_into()?);
+    let username = String::from_utf8(buf[12..].to_vec())?;
+    Ok((timestamp, local_port, remote_port, username))
+}
+
+/// Parse a hex-encoded 32-byte AES-256 key, as supplied on the command line.
+pub fn key_from_hex(hex: &str) -> Result<[u8; 32], Box<dyn Error + Send + Sync>> {
+    let bytes = hex_decode(hex)?;
+    bytes
+        .try_into()
+        .map_err(|_| "ident key must be exactly 32 bytes (64 hex characters)".into())
+}
+
+/// Render bytes as lowercase hex.
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parse a lowercase (or uppercase) hex string back into bytes.
+fn hex_decode(s: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let bytes = s.as_bytes();
+    if bytes.len() % 2 != 0 {
+        return Err("hex token has an odd number of characters".into());
+    }
+    // Chunk the raw bytes rather than slicing `s` by byte index: a multibyte UTF-8
+    // character in `s` would otherwise land on a non-char-boundary index and panic.
+    bytes
+        .chunks(2)
+        .map(|chunk| -> Result<u8, Box<dyn Error + Send + Sync>> {
+            let pair = std::str::from_utf8(chunk)?;
+            Ok(u8::from_str_radix(pair, 16)?)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_roundtrips_through_encode_and_decode() {
+        let key = [0x42; 32];
+        let token = encode_token(&key, 1234, 80, "alice").unwrap();
+        let decoded = decode_token(&key, &token).unwrap();
+        assert_eq!(decoded.local_port, 1234);
+        assert_eq!(decoded.remote_port, 80);
+        assert_eq!(decoded.username, "alice");
+    }
+
+    #[test]
+    fn decode_token_rejects_wrong_key() {
+        let token = encode_token(&[0x11; 32], 1, 2, "bob").unwrap();
+        assert!(decode_token(&[0x22; 32], &token).is_err());
+    }
+
+    #[test]
+    fn hex_decode_rejects_odd_length() {
+        assert!(hex_decode("abc").is_err());
+    }
+
+    #[test]
+    fn hex_decode_rejects_non_hex_without_panicking() {
+        assert!(hex_decode("zz").is_err());
+        // "aé0" is 4 bytes (1 + 2 + 1), so the 2-byte chunking splits the multibyte 'é'
+        // across a chunk boundary. That must return an error, not panic on a
+        // non-char-boundary slice as slicing `s` by byte index (`&s[i..i+2]`) would.
+        assert!(hex_decode("aé0").is_err());
+    }
+
+    #[test]
+    fn hex_encode_decode_roundtrips() {
+        let bytes = vec![0x00, 0x7f, 0xff, 0x10];
+        assert_eq!(hex_decode(&hex_encode(&bytes)).unwrap(), bytes);
+    }
+}